@@ -8,6 +8,10 @@ enum MarioConsumables {
 
 #[derive(Clone, Copy, PartialEq)]
 enum AliveStates {
+    /// The `AliveMario` composite state itself. Never a reachable
+    /// `current_state`; only returned from [StateBehavior::parent] so that
+    /// `SmallMario` and every `BigMario` sub-state share its `enter`/`exit`.
+    Base,
     SmallMario,
     BigMario(BigMarioStates),
 }
@@ -61,6 +65,8 @@ impl StateBehavior for States {
 
     fn enter(&self, context: &mut Self::Context) {
         match self {
+            States::AliveMario(AliveStates::Base) => {}
+
             States::AliveMario(AliveStates::SmallMario) => context.number_of_coins = 0,
 
             States::AliveMario(AliveStates::BigMario(BigMarioStates::SuperMario)) => {
@@ -79,6 +85,23 @@ impl StateBehavior for States {
         }
     }
 
+    fn exit(&self, context: &mut Self::Context) {
+        // Shared teardown for the whole `AliveMario` region: the coin tally
+        // is wiped out the moment Mario dies, regardless of which alive
+        // sub-state he died from.
+        if let States::AliveMario(AliveStates::Base) = self {
+            context.number_of_coins = 0;
+        }
+    }
+
+    fn parent(&self) -> Option<Self::State> {
+        match self {
+            States::AliveMario(AliveStates::Base) => None,
+            States::AliveMario(_) => Some(States::AliveMario(AliveStates::Base)),
+            States::DeadMario => None,
+        }
+    }
+
     fn handle_event(
         &self,
         event: &Self::Event<'_>,
@@ -133,6 +156,7 @@ impl Mario {
         Self {
             current_state: Default::default(),
             context: Default::default(),
+            observer: None,
         }
     }
 
@@ -1,7 +1,13 @@
-use rust_sfsm::{StateBehavior, rust_sfsm};
+use std::{cell::Cell, rc::Rc};
+
+use rust_sfsm::{StateBehavior, StateMachine, rust_sfsm};
+
+/// Maximum number of times the protocol may be locked.
+const MAX_LOCKS: u16 = 2;
 
 /// List of protocol states.
-#[derive(Clone, Copy, Default, PartialEq)]
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 enum States {
     #[default]
     Init,
@@ -11,7 +17,6 @@ enum States {
 }
 
 /// List of protocol events.
-#[derive(Clone, Copy, PartialEq)]
 enum Events {
     Create,
     Open,
@@ -20,62 +25,170 @@ enum Events {
     Unlock,
 }
 
-/// Protocol state machine context.
-#[derive(Default)]
+/// Protocol state machine context (data shared between states).
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Context {
     lock_counter: u16,
 }
 
 impl StateBehavior for States {
-    type State = States;
-    type Event = Events;
+    type State = Self;
+    type Event<'a> = Events;
     type Context = Context;
 
-    fn enter(&self, _context: &mut Self::Context) {
+    fn enter(&self, context: &mut Self::Context) {
         if self == &States::Locked {
-            _context.lock_counter += 1
+            context.lock_counter += 1
         }
     }
 
-    fn handle(&self, event: &Self::Event, _context: &mut Self::Context) -> Option<Self::State> {
-        match (self, event) {
-            (&States::Init, &Events::Create) => Some(States::Opened),
-            (&States::Opened, &Events::Close) => Some(States::Closed),
-            (&States::Closed, &Events::Open) => Some(States::Opened),
-            (&States::Closed, &Events::Lock) => Some(States::Locked),
-            (&States::Locked, &Events::Unlock) => Some(States::Closed),
-            _ => None,
+    fn guard(&self, event: &Self::Event<'_>, _target: &Self::State, context: &Self::Context) -> bool {
+        // Only allow the protocol to be locked a bounded number of times.
+        !matches!((self, event), (States::Closed, Events::Lock)) || context.lock_counter < MAX_LOCKS
+    }
+
+    fn on_transition(
+        &self,
+        target: &Self::State,
+        _event: &Self::Event<'_>,
+        _context: &mut Self::Context,
+    ) {
+        if let (States::Closed, States::Locked) = (self, target) {
+            println!("locking protocol");
         }
     }
+
+    fn handle_event(
+        &self,
+        event: &Self::Event<'_>,
+        _context: &mut Self::Context,
+    ) -> Option<Self::State> {
+        self.handle_event_from_transitions(event)
+    }
 }
 
+#[rust_sfsm(
+    states = States,
+    context = Context,
+    events = Events,
+    transitions = [
+        Init -(Create)-> Opened,
+        Opened -(Close)-> Closed,
+        Closed -(Open)-> Opened,
+        Closed -(Lock)-> Locked,
+        Locked -(Unlock)-> Closed,
+    ],
+    typestate,
+)]
+struct Protocol {}
+
 impl Protocol {
+    fn new() -> Self {
+        Self {
+            current_state: Default::default(),
+            context: Default::default(),
+            observer: None,
+        }
+    }
+
     /// Get number of protocol locking operations.
     fn lock_counter(&self) -> u16 {
         self.context.lock_counter
     }
 }
 
-rust_sfsm!(Protocol, States, Events, Context);
-
 fn main() {
     let mut protocol = Protocol::new();
 
+    // Count every committed transition (the guard-vetoed attempt below is
+    // still observed, but with `from == to`, so it is not counted), and
+    // separately count how many came through `transit` (no event to report)
+    // rather than `handle_event`.
+    let transitions = Rc::new(Cell::new(0u32));
+    let direct_transitions = Rc::new(Cell::new(0u32));
+    let transitions_observed = Rc::clone(&transitions);
+    let direct_transitions_observed = Rc::clone(&direct_transitions);
+    protocol.set_observer(Some(Box::new(move |from, to, event| {
+        if from != to {
+            transitions_observed.set(transitions_observed.get() + 1);
+            if event.is_none() {
+                direct_transitions_observed.set(direct_transitions_observed.get() + 1);
+            }
+        }
+    })));
+
     assert!(protocol.current_state() == States::Init);
 
-    protocol.handle(Events::Create);
+    protocol.handle_event(&Events::Create);
     assert!(protocol.current_state() == States::Opened);
 
-    protocol.handle(Events::Close);
+    protocol.handle_event(&Events::Close);
     assert!(protocol.current_state() == States::Closed);
 
-    protocol.handle(Events::Lock);
+    protocol.handle_event(&Events::Lock);
     assert!(protocol.current_state() == States::Locked);
     assert!(protocol.lock_counter() == 1);
 
-    protocol.handle(Events::Unlock);
+    protocol.handle_event(&Events::Unlock);
+    assert!(protocol.current_state() == States::Closed);
+
+    protocol.handle_event(&Events::Lock);
+    assert!(protocol.current_state() == States::Locked);
+    assert!(protocol.lock_counter() == 2);
+
+    protocol.handle_event(&Events::Unlock);
     assert!(protocol.current_state() == States::Closed);
 
-    protocol.handle(Events::Open);
+    // The lock quota is exhausted: the guard vetoes the transition and the
+    // protocol stays closed.
+    protocol.handle_event(&Events::Lock);
+    assert!(protocol.current_state() == States::Closed);
+    assert!(protocol.lock_counter() == 2);
+
+    assert!(transitions.get() == 6);
+    assert!(direct_transitions.get() == 0);
+
+    // `StateMachine::transit` bypasses `handle_event`/`guard` entirely, but
+    // the observer still sees it committed, with no event to report.
+    protocol.transit(States::Opened);
     assert!(protocol.current_state() == States::Opened);
+    assert!(transitions.get() == 7);
+    assert!(direct_transitions.get() == 1);
+
+    // The typestate twin runs the exact same guard/on_transition path, so the
+    // lock quota vetoes it too: the first two locks succeed (`Ok`), and the
+    // third comes back `Err` with the machine still in `Closed` instead of
+    // silently advancing to `Locked`.
+    use protocol_typestate::{Init, Machine};
+
+    let machine: Machine<Init> = Machine::new(Context::default());
+    let machine = machine.create().ok().unwrap();
+    let machine = machine.close().ok().unwrap();
+
+    let machine = machine.lock().ok().unwrap();
+    assert!(machine.context().lock_counter == 1);
+    let machine = machine.unlock().ok().unwrap();
+
+    let machine = machine.lock().ok().unwrap();
+    assert!(machine.context().lock_counter == 2);
+    let machine = machine.unlock().ok().unwrap();
+
+    let machine = match machine.lock() {
+        Ok(_) => panic!("the lock quota should have vetoed this transition"),
+        Err(machine) => machine,
+    };
+    assert!(machine.context().lock_counter == 2);
+
+    // Persist and resume the machine without running any `enter`/`exit`.
+    #[cfg(feature = "serde")]
+    {
+        let snapshot = protocol.snapshot();
+        let serialized = serde_json::to_string(&snapshot).unwrap();
+        let deserialized: rust_sfsm::Snapshot<States> = serde_json::from_str(&serialized).unwrap();
+        let restored = Protocol::restore(deserialized).unwrap();
+
+        assert!(restored.current_state() == protocol.current_state());
+        assert!(restored.lock_counter() == protocol.lock_counter());
+    }
 }
@@ -1,16 +1,118 @@
-use darling::FromMeta;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
-    Field, FieldMutability, Fields, FieldsNamed, Ident, Item, ItemStruct, Path, Type, TypePath,
-    Visibility, parse, parse_macro_input, token::Colon,
+    Field, FieldMutability, Fields, FieldsNamed, Ident, Item, ItemStruct, Path, Token, Type,
+    TypePath, Visibility,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    token::Colon,
 };
 
-#[derive(Debug, FromMeta)]
-#[darling(derive_syn_parse)]
+/// One `source -(event)-> target` rule of a `transitions = [...]` table.
+struct TransitionRule {
+    source: Ident,
+    event: Ident,
+    target: Ident,
+}
+
+impl Parse for TransitionRule {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let source: Ident = input.parse()?;
+        input.parse::<Token![-]>()?;
+
+        let event_paren;
+        syn::parenthesized!(event_paren in input);
+        let event: Ident = event_paren.parse()?;
+
+        input.parse::<Token![->]>()?;
+        let target: Ident = input.parse()?;
+
+        Ok(TransitionRule {
+            source,
+            event,
+            target,
+        })
+    }
+}
+
+/// Parsed `#[rust_sfsm(...)]` argument list.
+///
+/// Hand-rolled rather than `darling::FromMeta` (as this crate used before
+/// `transitions` was added): `FromMeta`/`NestedMeta` parses each item
+/// through `syn::Meta`'s grammar (path, `key = literal-or-path`, or a
+/// parenthesized list of more `Meta`s), which has no room for an arbitrary
+/// infix token sequence like `source -(event)-> target` inside a bracketed
+/// list — there's no `Meta` shape to assign that to. And `derive_syn_parse`
+/// generates one `Parse` impl across the whole struct in a single pass, so
+/// `states`/`context`/`events` couldn't stay on the `Meta`-based path while
+/// only `transitions` dropped down to raw tokens; once one field needed
+/// custom grammar, the whole attribute had to move off `darling`.
 struct Args {
     states: Path,
     context: Path,
+    events: Option<Path>,
+    transitions: Option<Punctuated<TransitionRule, Token![,]>>,
+    typestate: bool,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut states = None;
+        let mut context = None;
+        let mut events = None;
+        let mut transitions = None;
+        let mut typestate = false;
+
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+
+            if !input.peek(Token![=]) {
+                // Bare flags, e.g. `typestate`, carry no `= value`.
+                match key.to_string().as_str() {
+                    "typestate" => typestate = true,
+                    other => {
+                        return Err(syn::Error::new(
+                            key.span(),
+                            format!("unknown `rust_sfsm` flag `{other}`"),
+                        ));
+                    }
+                }
+            } else {
+                input.parse::<Token![=]>()?;
+
+                match key.to_string().as_str() {
+                    "states" => states = Some(input.parse()?),
+                    "context" => context = Some(input.parse()?),
+                    "events" => events = Some(input.parse()?),
+                    "transitions" => {
+                        let list;
+                        syn::bracketed!(list in input);
+                        transitions = Some(Punctuated::parse_terminated(&list)?);
+                    }
+                    other => {
+                        return Err(syn::Error::new(
+                            key.span(),
+                            format!("unknown `rust_sfsm` argument `{other}`"),
+                        ));
+                    }
+                }
+            }
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        Ok(Args {
+            states: states.ok_or_else(|| input.error("missing required argument `states`"))?,
+            context: context.ok_or_else(|| input.error("missing required argument `context`"))?,
+            events,
+            transitions,
+            typestate,
+        })
+    }
 }
 
 /// # Rust-SFSM Attribute Macro.
@@ -98,6 +200,7 @@ struct Args {
 /// struct Protocol {
 ///     current_state: States,
 ///     context: Context,
+///     observer: Option<::rust_sfsm::Observer<States>>,
 /// }
 ///
 /// impl ::rust_sfsm::StateMachine<States> for Protocol {
@@ -109,33 +212,161 @@ struct Args {
 ///         &mut self,
 ///         event: &<States as ::rust_sfsm::StateBehavior>::Event<'_>,
 ///     ) {
+///         let from = self.current_state;
+///
 ///         if let Some(next_state) = self
 ///             .current_state
 ///             .handle_event(event, &mut self.context)
 ///         {
-///             self.transit(next_state)
+///             self.current_state =
+///                 ::rust_sfsm::transit_on_event(self.current_state, next_state, event, &mut self.context);
+///         }
+///
+///         if let Some(observer) = &mut self.observer {
+///             observer(&from, &self.current_state, Some(event));
 ///         }
 ///     }
 ///
 ///     fn transit(&mut self, new_state: <States as ::rust_sfsm::StateBehavior>::State) {
-///         self.current_state.exit(&mut self.context);
-///         self.current_state = new_state;
-///         self.current_state.enter(&mut self.context);
+///         let from = self.current_state;
+///
+///         self.current_state = ::rust_sfsm::transit(self.current_state, new_state, &mut self.context);
+///
+///         if let Some(observer) = &mut self.observer {
+///             observer(&from, &self.current_state, None);
+///         }
 ///     }
 ///
 ///     fn force_state(&mut self, new_state: <States as ::rust_sfsm::StateBehavior>::State) {
 ///         self.current_state = new_state;
 ///     }
+///
+///     fn set_observer(&mut self, observer: Option<::rust_sfsm::Observer<States>>) {
+///         self.observer = observer;
+///     }
 /// }
 /// ```
+///
+/// ## Transition Table
+///
+/// Instead of hand-writing the `match` inside `handle_event`, the common
+/// case of a flat transition table can be declared inline with a
+/// `transitions = [ source -(event)-> target, ... ]` list (this requires
+/// also naming the `events` type, since it no longer appears anywhere else
+/// in the attribute):
+///
+/// ```rust
+/// # use rust_sfsm::{StateBehavior, rust_sfsm};
+/// # #[derive(Clone, Copy, Default, PartialEq)]
+/// # enum States { #[default] Init, Opened, Closed, Locked }
+/// # #[derive(Clone, Copy, PartialEq)]
+/// # enum Events { Create, Open, Close, Lock, Unlock }
+/// # #[derive(Default)]
+/// # struct Context { lock_counter: u16 }
+/// #[rust_sfsm(
+///     states = States,
+///     context = Context,
+///     events = Events,
+///     transitions = [
+///         Init -(Create)-> Opened,
+///         Opened -(Close)-> Closed,
+///         Closed -(Open)-> Opened,
+///         Closed -(Lock)-> Locked,
+///         Locked -(Unlock)-> Closed,
+///     ],
+/// )]
+/// struct Protocol {}
+///
+/// impl StateBehavior for States {
+///     type State = Self;
+///     type Event<'a> = Events;
+///     type Context = Context;
+///
+///     fn handle_event(
+///         &self,
+///         event: &Self::Event<'_>,
+///         _context: &mut Self::Context,
+///     ) -> Option<Self::State> {
+///         self.handle_event_from_transitions(event)
+///     }
+/// }
+/// ```
+///
+/// This generates an `impl States { fn handle_event_from_transitions(...) }`
+/// inherent method holding the match arms; users still write `enter`/`exit`
+/// (and wire `handle_event` to the generated method) by hand.
+///
+/// ## Typestate Mode
+///
+/// Adding the bare `typestate` flag alongside `transitions` additionally
+/// generates a `<struct>_typestate` module with a compile-time checked twin
+/// of the machine: one marker type per state variant named in `transitions`,
+/// and a `Machine<S>` wrapper that owns the `Context`. Each declared rule
+/// becomes a method consuming `Machine<Source>` and returning
+/// `Result<Machine<Target>, Machine<Source>>` — the same `guard`, `exit`,
+/// `on_transition` and `enter` hooks run as in the dynamic `StateMachine`, so
+/// a vetoed guard comes back as `Err` with the machine still in `Source`.
+/// An undeclared transition is a compile error and a moved-from machine
+/// can't be reused:
+///
+/// ```rust
+/// # use rust_sfsm::{StateBehavior, rust_sfsm};
+/// # #[derive(Clone, Copy, Default, PartialEq)]
+/// # enum States { #[default] Init, Opened, Closed, Locked }
+/// # #[derive(Clone, Copy, PartialEq)]
+/// # enum Events { Create, Open, Close, Lock, Unlock }
+/// # #[derive(Default)]
+/// # struct Context { lock_counter: u16 }
+/// # impl StateBehavior for States {
+/// #     type State = Self;
+/// #     type Event<'a> = Events;
+/// #     type Context = Context;
+/// #     fn handle_event(&self, event: &Self::Event<'_>, _c: &mut Self::Context) -> Option<Self::State> {
+/// #         self.handle_event_from_transitions(event)
+/// #     }
+/// # }
+/// #[rust_sfsm(
+///     states = States,
+///     context = Context,
+///     events = Events,
+///     transitions = [Init -(Create)-> Opened, Opened -(Close)-> Closed],
+///     typestate,
+/// )]
+/// struct Protocol {}
+///
+/// # fn main() {
+/// use protocol_typestate::{Init, Machine};
+///
+/// let machine: Machine<Init> = Machine::new(Context::default());
+/// let machine = machine.create().ok().unwrap(); // now a Machine<Opened>
+/// // machine.create() again wouldn't compile: Opened has no `create` method.
+/// # }
+/// ```
+///
+/// ## Persistence
+///
+/// Behind the `serde` cargo feature, the generated struct also gets
+/// `Serialize`/`Deserialize` impls covering `current_state` and `context`
+/// (the `observer` callback is runtime wiring, never persisted), plus
+/// [StateMachine::snapshot] and [StateMachine::restore] methods.
+/// Deserializing (or calling `restore` directly) goes through the same
+/// `enter`/`exit`-free path as [StateMachine::force_state], and runs
+/// [StateBehavior::validate_restored_state] first so a snapshot naming a
+/// state retired from the transition map is rejected instead of silently
+/// resumed:
+///
+/// ```rust,ignore
+/// #[rust_sfsm(states = States, context = Context)]
+/// struct Protocol {}
+///
+/// let protocol = Protocol::new();
+/// let serialized = serde_json::to_string(&protocol)?;
+/// let restored: Protocol = serde_json::from_str(&serialized)?;
+/// assert!(restored.current_state() == protocol.current_state());
+/// ```
 #[proc_macro_attribute]
 pub fn rust_sfsm(args: TokenStream, input: TokenStream) -> TokenStream {
-    let args: Args = match parse(args) {
-        Ok(args) => args,
-        Err(e) => {
-            return e.into_compile_error().into();
-        }
-    };
+    let args = parse_macro_input!(args as Args);
 
     let input = parse_macro_input!(input as Item);
 
@@ -147,10 +378,30 @@ pub fn rust_sfsm(args: TokenStream, input: TokenStream) -> TokenStream {
             // add state machine impl
             let struct_ident = &item_struct.ident;
             let trait_impl = generate_state_machine_impl(struct_ident, &args);
+            let serde_impls = generate_serde_impls(struct_ident, &args);
+
+            // add the generated transition table, if any
+            let transitions_impl = match generate_transitions_impl(&args) {
+                Ok(impl_block) => impl_block,
+                Err(e) => return e.into_compile_error().into(),
+            };
+
+            // add the generated typestate machine, if requested
+            let typestate_impl = if args.typestate {
+                match generate_typestate_impl(struct_ident, &args) {
+                    Ok(module) => module,
+                    Err(e) => return e.into_compile_error().into(),
+                }
+            } else {
+                proc_macro2::TokenStream::new()
+            };
 
             quote! {
                 #item_struct
                 #trait_impl
+                #serde_impls
+                #transitions_impl
+                #typestate_impl
             }
         }
 
@@ -190,8 +441,19 @@ fn add_fields(item_struct: &mut ItemStruct, args: &Args) {
             }),
         };
 
+        let states_type = &args.states;
+        let observer_field = syn::Field {
+            attrs: Vec::new(),
+            vis: Visibility::Inherited,
+            mutability: FieldMutability::None,
+            ident: Some(Ident::new("observer", proc_macro2::Span::call_site())),
+            colon_token: Some(Colon::default()),
+            ty: syn::parse_quote!(Option<::rust_sfsm::Observer<#states_type>>),
+        };
+
         named.push(current_state_field);
         named.push(context_field);
+        named.push(observer_field);
     }
 }
 
@@ -206,20 +468,316 @@ fn generate_state_machine_impl(struct_ident: &Ident, args: &Args) -> proc_macro2
             }
 
             fn handle_event(&mut self, event: &<#states_type as ::rust_sfsm::StateBehavior>::Event<'_>) {
+                let from = self.current_state;
+
                 if let Some(next_state) = self.current_state.handle_event(event, &mut self.context) {
-                    self.transit(next_state)
+                    self.current_state = ::rust_sfsm::transit_on_event(self.current_state, next_state, event, &mut self.context);
+                }
+
+                if let Some(observer) = &mut self.observer {
+                    observer(&from, &self.current_state, Some(event));
                 }
             }
 
             fn transit(&mut self, new_state: <#states_type as ::rust_sfsm::StateBehavior>::State) {
-                self.current_state.exit(&mut self.context);
-                self.current_state = new_state;
-                self.current_state.enter(&mut self.context);
+                let from = self.current_state;
+
+                self.current_state = ::rust_sfsm::transit(self.current_state, new_state, &mut self.context);
+
+                if let Some(observer) = &mut self.observer {
+                    observer(&from, &self.current_state, None);
+                }
             }
 
             fn force_state(&mut self, new_state: <#states_type as ::rust_sfsm::StateBehavior>::State) {
                 self.current_state = new_state;
             }
+
+            fn set_observer(&mut self, observer: Option<::rust_sfsm::Observer<#states_type>>) {
+                self.observer = observer;
+            }
+
+            #[cfg(feature = "serde")]
+            fn snapshot(&self) -> ::rust_sfsm::Snapshot<#states_type>
+            where
+                <#states_type as ::rust_sfsm::StateBehavior>::Context: Clone,
+            {
+                ::rust_sfsm::Snapshot {
+                    state: self.current_state,
+                    context: self.context.clone(),
+                }
+            }
+
+            #[cfg(feature = "serde")]
+            fn restore(
+                snapshot: ::rust_sfsm::Snapshot<#states_type>,
+            ) -> Result<Self, ::rust_sfsm::RestoreError<<#states_type as ::rust_sfsm::StateBehavior>::State>> {
+                let current_state =
+                    <#states_type as ::rust_sfsm::StateBehavior>::validate_restored_state(snapshot.state)?;
+
+                Ok(Self {
+                    current_state,
+                    context: snapshot.context,
+                    observer: None,
+                })
+            }
         }
     }
 }
+
+/// Generates manual `Serialize`/`Deserialize` impls for the generated struct,
+/// covering `current_state` and `context` only (the `observer` callback is
+/// runtime wiring, not persisted state). Routes deserialization through
+/// [generate_state_machine_impl]'s `restore` so a rejected state surfaces as
+/// a proper deserialize error instead of silently loading.
+///
+/// `#[cfg(feature = "serde")]` is baked into the emitted tokens, not applied
+/// to this function: the feature gate belongs to the crate consuming the
+/// macro, which this proc-macro crate has no cfg visibility into.
+fn generate_serde_impls(struct_ident: &Ident, args: &Args) -> proc_macro2::TokenStream {
+    let states_type = &args.states;
+
+    quote! {
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for #struct_ident
+        where
+            <#states_type as ::rust_sfsm::StateBehavior>::State: ::serde::Serialize,
+            <#states_type as ::rust_sfsm::StateBehavior>::Context: ::serde::Serialize + Clone,
+        {
+            fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+            where
+                Ser: ::serde::Serializer,
+            {
+                ::serde::Serialize::serialize(
+                    &::rust_sfsm::Snapshot {
+                        state: self.current_state,
+                        context: self.context.clone(),
+                    },
+                    serializer,
+                )
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> ::serde::Deserialize<'de> for #struct_ident
+        where
+            <#states_type as ::rust_sfsm::StateBehavior>::State:
+                ::serde::de::DeserializeOwned + ::core::fmt::Debug,
+            <#states_type as ::rust_sfsm::StateBehavior>::Context: ::serde::de::DeserializeOwned,
+        {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                let snapshot =
+                    <::rust_sfsm::Snapshot<#states_type> as ::serde::Deserialize>::deserialize(deserializer)?;
+
+                <Self as ::rust_sfsm::StateMachine<#states_type>>::restore(snapshot)
+                    .map_err(::serde::de::Error::custom)
+            }
+        }
+    }
+}
+
+/// Generates `impl #states_type { fn handle_event_from_transitions(...) }`
+/// from the `transitions = [...]` list, if one was given.
+fn generate_transitions_impl(args: &Args) -> syn::Result<proc_macro2::TokenStream> {
+    let Some(transitions) = &args.transitions else {
+        return Ok(proc_macro2::TokenStream::new());
+    };
+
+    let Some(events_type) = &args.events else {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`events` is required when `transitions` is used",
+        ));
+    };
+
+    let states_type = &args.states;
+
+    let arms = transitions.iter().map(|rule| {
+        let TransitionRule {
+            source,
+            event,
+            target,
+        } = rule;
+
+        quote! {
+            (&#states_type::#source, &#events_type::#event) => Some(#states_type::#target),
+        }
+    });
+
+    Ok(quote! {
+        impl #states_type {
+            /// Transition table generated by the `rust_sfsm` attribute
+            /// macro's `transitions = [...]` list.
+            fn handle_event_from_transitions(&self, event: &#events_type) -> Option<#states_type> {
+                match (self, event) {
+                    #(#arms)*
+                    _ => None,
+                }
+            }
+        }
+    })
+}
+
+/// Generates a `#struct_ident_typestate` module holding a zero-cost,
+/// compile-time-checked twin of the machine, from the `transitions = [...]`
+/// list. Only reachable when `typestate` is set.
+fn generate_typestate_impl(
+    struct_ident: &Ident,
+    args: &Args,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let Some(transitions) = &args.transitions else {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`typestate` requires a `transitions = [...]` list to generate methods from",
+        ));
+    };
+
+    let states_type = &args.states;
+    let context_type = &args.context;
+
+    // One marker type per state variant referenced by the transition table.
+    let mut markers: Vec<&Ident> = Vec::new();
+    for rule in transitions {
+        for ident in [&rule.source, &rule.target] {
+            if !markers.contains(&ident) {
+                markers.push(ident);
+            }
+        }
+    }
+
+    let marker_defs = markers.iter().map(|ident| {
+        quote! {
+            /// Typestate marker for the corresponding state variant.
+            pub struct #ident;
+        }
+    });
+
+    let Some(events_type) = &args.events else {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`typestate` requires an `events` type (needed to evaluate `guard`/`on_transition`)",
+        ));
+    };
+
+    let transition_methods = transitions.iter().map(|rule| {
+        let TransitionRule {
+            source,
+            event,
+            target,
+        } = rule;
+        let method = Ident::new(&to_snake_case(&event.to_string()), event.span());
+        let doc = format!(
+            "Generated from the `{source} -({event})-> {target}` rule. Runs the same \
+             guard/on_transition/enter/exit path as the dynamic `StateMachine`, including \
+             any `parent` ancestors; returns `Err` with the machine still in `{source}` if \
+             the guard vetoes the transition."
+        );
+
+        quote! {
+            impl Machine<#source> {
+                #[doc = #doc]
+                pub fn #method(self) -> Result<Machine<#target>, Machine<#source>> {
+                    let mut context = self.context;
+                    let event = #events_type::#event;
+
+                    if !<#states_type as ::rust_sfsm::StateBehavior>::guard(
+                        &#states_type::#source,
+                        &event,
+                        &#states_type::#target,
+                        &context,
+                    ) {
+                        return Err(Machine {
+                            context,
+                            _state: ::core::marker::PhantomData,
+                        });
+                    }
+
+                    let next = ::rust_sfsm::transit_on_event(
+                        #states_type::#source,
+                        #states_type::#target,
+                        &event,
+                        &mut context,
+                    );
+
+                    if next == #states_type::#target {
+                        Ok(Machine {
+                            context,
+                            _state: ::core::marker::PhantomData,
+                        })
+                    } else {
+                        Err(Machine {
+                            context,
+                            _state: ::core::marker::PhantomData,
+                        })
+                    }
+                }
+            }
+        }
+    });
+
+    let mod_ident = Ident::new(
+        &format!("{}_typestate", to_snake_case(&struct_ident.to_string())),
+        struct_ident.span(),
+    );
+
+    let mod_doc = format!(
+        "Compile-time-checked twin of `{struct_ident}`, generated from its `transitions` \
+         table. Each declared transition is a method consuming the machine in its source \
+         state and returning `Ok` of it in the target state (or `Err` of it still in the \
+         source state if `guard` vetoes); an undeclared transition simply doesn't compile, \
+         and the borrow checker rejects reuse of a machine already moved into one. Guard, \
+         on_transition, enter, exit, and any `parent` ancestors all run exactly as they \
+         would through the dynamic `StateMachine`, which is still available for cases \
+         where the state isn't known statically."
+    );
+
+    Ok(quote! {
+        #[doc = #mod_doc]
+        mod #mod_ident {
+            use super::*;
+
+            #(#marker_defs)*
+
+            pub struct Machine<S> {
+                context: #context_type,
+                _state: ::core::marker::PhantomData<S>,
+            }
+
+            impl<S> Machine<S> {
+                pub fn new(context: #context_type) -> Self {
+                    Self {
+                        context,
+                        _state: ::core::marker::PhantomData,
+                    }
+                }
+
+                pub fn context(&self) -> &#context_type {
+                    &self.context
+                }
+            }
+
+            #(#transition_methods)*
+        }
+    })
+}
+
+/// Converts a `PascalCase` identifier into `snake_case`.
+fn to_snake_case(s: &str) -> String {
+    let mut out = String::new();
+
+    for (i, ch) in s.chars().enumerate() {
+        if ch.is_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.extend(ch.to_lowercase());
+        } else {
+            out.push(ch);
+        }
+    }
+
+    out
+}
@@ -76,6 +76,179 @@ pub trait StateBehavior {
 
     /// State exit.
     fn exit(&self, _context: &mut Self::Context) {}
+
+    /// Containing (parent) state, if any.
+    ///
+    /// Returning `Some` nests this state inside another one, building a
+    /// containment tree that [transit] walks to run `enter`/`exit` on every
+    /// ancestor affected by a transition, not just the leaf state.
+    fn parent(&self) -> Option<Self::State> {
+        None
+    }
+
+    /// Guard checked before a candidate transition is committed.
+    ///
+    /// Called with the current state as `self` once [StateBehavior::handle_event] has
+    /// produced a candidate `target`, but before `exit`/`enter` run. Returning
+    /// `false` vetoes the transition entirely: no `exit`, `on_transition`, or
+    /// `enter` call happens, and the machine stays in `self`.
+    fn guard(&self, _event: &Self::Event<'_>, _target: &Self::State, _context: &Self::Context) -> bool {
+        true
+    }
+
+    /// Action run as a transition executes, after `exit` and before the new
+    /// state is assigned (and before its `enter` runs).
+    fn on_transition(&self, _target: &Self::State, _event: &Self::Event<'_>, _context: &mut Self::Context) {}
+
+    /// Validates a state loaded from a [Snapshot] before [StateMachine::restore]
+    /// installs it, bypassing `enter`/`exit` the same way
+    /// [StateMachine::force_state] does.
+    ///
+    /// The default accepts every state. Override to reject (or remap) a
+    /// variant retired from the transition map, so a stale snapshot can't
+    /// resume a machine into a state the current code no longer understands.
+    ///
+    /// Note that [RestoreError] only implements `Debug`/`Display` when
+    /// `Self::State` does, which isn't required elsewhere in this trait — add
+    /// `#[derive(Debug)]` to your state type if you intend to `.unwrap()` or
+    /// `.expect(..)` a failed [StateMachine::restore].
+    #[cfg(feature = "serde")]
+    fn validate_restored_state(state: Self::State) -> Result<Self::State, RestoreError<Self::State>> {
+        Ok(state)
+    }
+}
+
+/// Runs a hierarchical (UML-style) transition from `current` to `target`.
+///
+/// Both states' ancestor chains (via [StateBehavior::parent]) are walked up
+/// to their lowest common ancestor (LCA). `exit` is called on `current` and
+/// every ancestor up to, but not including, the LCA; `enter` is then called
+/// on every state below the LCA down to `target`, outermost first. A
+/// self-transition (`current == target`) takes the LCA to be `current`'s own
+/// parent, so the state is exited and re-entered; a transition to a direct
+/// ancestor or into a descendant only runs the applicable half.
+///
+/// This is called by the [rust_sfsm] macro's generated
+/// `StateMachine::transit` implementation and isn't meant to be called
+/// directly.
+pub fn transit<T>(current: T, target: T, context: &mut T::Context) -> T
+where
+    T: StateBehavior<State = T> + PartialEq + Copy,
+{
+    let lca = lowest_common_ancestor_for_transition(current, target);
+
+    exit_up_to(current, lca, context);
+    enter_down_to(target, lca, context);
+
+    target
+}
+
+/// Like [transit], but evaluates [StateBehavior::guard] first and runs
+/// [StateBehavior::on_transition] after `exit` and before the new state is
+/// entered. The guard is checked against `current` with `event` and `target`;
+/// if it returns `false` the transition is vetoed and `current` is returned
+/// unchanged, with no `exit`, `on_transition`, or `enter` call at all.
+///
+/// This is called by the [rust_sfsm] macro's generated
+/// `StateMachine::handle_event` implementation and isn't meant to be called
+/// directly.
+pub fn transit_on_event<T>(
+    current: T,
+    target: T,
+    event: &T::Event<'_>,
+    context: &mut T::Context,
+) -> T
+where
+    T: StateBehavior<State = T> + PartialEq + Copy,
+{
+    if !current.guard(event, &target, context) {
+        return current;
+    }
+
+    let lca = lowest_common_ancestor_for_transition(current, target);
+
+    exit_up_to(current, lca, context);
+    current.on_transition(&target, event, context);
+    enter_down_to(target, lca, context);
+
+    target
+}
+
+fn lowest_common_ancestor_for_transition<T>(current: T, target: T) -> Option<T>
+where
+    T: StateBehavior<State = T> + PartialEq + Copy,
+{
+    if current == target {
+        current.parent()
+    } else {
+        lowest_common_ancestor(current, target)
+    }
+}
+
+fn exit_up_to<T>(state: T, lca: Option<T>, context: &mut T::Context)
+where
+    T: StateBehavior<State = T> + PartialEq + Copy,
+{
+    let mut cursor = Some(state);
+    while let Some(state) = cursor {
+        if Some(state) == lca {
+            break;
+        }
+        state.exit(context);
+        cursor = state.parent();
+    }
+}
+
+fn lowest_common_ancestor<T>(mut a: T, mut b: T) -> Option<T>
+where
+    T: StateBehavior<State = T> + PartialEq + Copy,
+{
+    let mut depth_a = depth(a);
+    let mut depth_b = depth(b);
+
+    while depth_a > depth_b {
+        a = a.parent()?;
+        depth_a -= 1;
+    }
+
+    while depth_b > depth_a {
+        b = b.parent()?;
+        depth_b -= 1;
+    }
+
+    while a != b {
+        a = a.parent()?;
+        b = b.parent()?;
+    }
+
+    Some(a)
+}
+
+fn depth<T>(mut state: T) -> usize
+where
+    T: StateBehavior<State = T>,
+{
+    let mut depth = 0;
+    while let Some(parent) = state.parent() {
+        depth += 1;
+        state = parent;
+    }
+    depth
+}
+
+fn enter_down_to<T>(state: T, lca: Option<T>, context: &mut T::Context)
+where
+    T: StateBehavior<State = T> + PartialEq + Copy,
+{
+    if Some(state) == lca {
+        return;
+    }
+
+    if let Some(parent) = state.parent() {
+        enter_down_to(parent, lca, context);
+    }
+
+    state.enter(context);
 }
 
 /// Trait for the state machine behavior.
@@ -119,4 +292,95 @@ pub trait StateMachine<S: StateBehavior> {
     /// Force transition to a new state without calls to respectives
     /// `enter` and `exit` functions.
     fn force_state(&mut self, new_state: S::State);
+
+    /// Registers a listener invoked on every committed transition, by both
+    /// [transit] and [handle_event], as well as on every event
+    /// [handle_event] handles without committing a transition. The state
+    /// before and after the call is always passed; the triggering event is
+    /// passed as `Some` when called from `handle_event` and `None` when
+    /// called from `transit` (which has no event to offer). `from == to`
+    /// whenever no transition committed — that covers both an event
+    /// [StateBehavior::handle_event] didn't recognize at all, and a
+    /// candidate transition that [StateBehavior::guard] vetoed (the vetoed
+    /// target itself isn't surfaced; the machine stays in `from` either
+    /// way). Pass `None` to stop observing.
+    ///
+    /// [transit]: StateMachine::transit
+    /// [handle_event]: StateMachine::handle_event
+    fn set_observer(&mut self, observer: Option<Observer<S>>);
+
+    /// Snapshots the current state and context for persistence.
+    #[cfg(feature = "serde")]
+    fn snapshot(&self) -> Snapshot<S>
+    where
+        S::Context: Clone;
+
+    /// Restores a machine from a [Snapshot], routed through the same
+    /// `enter`/`exit`-free path as [force_state][StateMachine::force_state].
+    /// Fails if [StateBehavior::validate_restored_state] rejects the
+    /// snapshotted state; unwrapping that error requires `S::State: Debug`.
+    #[cfg(feature = "serde")]
+    fn restore(snapshot: Snapshot<S>) -> Result<Self, RestoreError<S::State>>
+    where
+        Self: Sized;
+}
+
+/// Callback signature for [StateMachine::set_observer]: `(from, to, event)`,
+/// with `event` as `None` when invoked from [StateMachine::transit] (which
+/// has no event to offer).
+///
+/// Stored as a boxed closure (may capture state) under the `std` feature, or
+/// as a plain function pointer under `no_std`.
+#[cfg(feature = "std")]
+pub type Observer<S> = std::boxed::Box<
+    dyn for<'a> FnMut(
+        &<S as StateBehavior>::State,
+        &<S as StateBehavior>::State,
+        Option<&<S as StateBehavior>::Event<'a>>,
+    ),
+>;
+
+/// Callback signature for [StateMachine::set_observer]: `(from, to, event)`,
+/// with `event` as `None` when invoked from [StateMachine::transit] (which
+/// has no event to offer).
+///
+/// Stored as a boxed closure (may capture state) under the `std` feature, or
+/// as a plain function pointer under `no_std`.
+#[cfg(not(feature = "std"))]
+pub type Observer<S> = fn(
+    &<S as StateBehavior>::State,
+    &<S as StateBehavior>::State,
+    Option<&<S as StateBehavior>::Event<'_>>,
+);
+
+/// Persisted form of a [StateMachine]'s `current_state` and `context`,
+/// produced by [StateMachine::snapshot] and consumed by [StateMachine::restore].
+///
+/// The `observer` callback, if any, is never part of this: it's runtime
+/// wiring, not machine state, and closures aren't serializable anyway.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(bound(
+    serialize = "S::State: serde::Serialize, S::Context: serde::Serialize",
+    deserialize = "S::State: serde::de::DeserializeOwned, S::Context: serde::de::DeserializeOwned"
+))]
+pub struct Snapshot<S: StateBehavior> {
+    pub state: S::State,
+    pub context: S::Context,
+}
+
+/// Error returned by [StateMachine::restore] when
+/// [StateBehavior::validate_restored_state] rejects the snapshotted state.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct RestoreError<State>(pub State);
+
+#[cfg(feature = "serde")]
+impl<State: core::fmt::Debug> core::fmt::Display for RestoreError<State> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "restored state rejected: {:?}", self.0)
+    }
 }
+
+#[cfg(all(feature = "serde", feature = "std"))]
+impl<State: core::fmt::Debug> std::error::Error for RestoreError<State> {}